@@ -0,0 +1,370 @@
+//! Runtime-tagged wrapper around [`Order`].
+//!
+//! The typestate pattern encodes an order's state in its type, which is
+//! exactly what we want when the code driving transitions knows what order
+//! they happen in. Some contexts don't have that luxury -- for example,
+//! [`replaying`](crate::events) a log of past events, or reacting to actions
+//! coming off a queue in whatever order they arrive -- so `AnyOrder` tags the
+//! state at runtime instead, with one variant per [`OrderState`](crate::OrderState).
+
+use crate::{
+    AwaitingPayment, AwaitingPickup, Boxing, Cancelled, Completed, InDelivery, Invalid, Order,
+    Packaging, PaymentFailed, Pending, PickingItems,
+};
+
+/// An order whose current state is tagged at runtime rather than encoded in
+/// its type
+pub enum AnyOrder {
+    Pending(Order<Pending>),
+    Invalid(Order<Invalid>),
+    AwaitingPayment(Order<AwaitingPayment>),
+    PaymentFailed(Order<PaymentFailed>),
+    Packaging(Order<Packaging<PickingItems>>),
+    Boxing(Order<Packaging<Boxing>>),
+    AwaitingPickup(Order<Packaging<AwaitingPickup>>),
+    InDelivery(Order<InDelivery>),
+    Completed(Order<Completed>),
+    Cancelled(Order<Cancelled>),
+}
+
+/// An action requested on an [`AnyOrder`] whose outcome depends on its
+/// current (runtime-only) state
+#[derive(Clone, Debug)]
+pub enum OrderAction {
+    /// Validate a pending order
+    Validate,
+    /// Submit the order, skipping validation
+    Submit,
+    /// Confirm that payment was received
+    ConfirmPayment,
+    /// Reject the order because payment could not be verified
+    RejectPayment { reason: String },
+    /// Finish picking items and start boxing them up
+    PickComplete,
+    /// Finish boxing the order
+    Boxed { box_count: u32 },
+    /// Ship the order
+    Ship { tracking_id: String },
+    /// Mark the order as completed
+    Complete,
+    /// Cancel the order
+    Cancel { reason: String },
+}
+
+/// An error produced when an [`OrderAction`] does not apply to an order's
+/// current state
+#[derive(Clone, Debug)]
+pub struct TransitionError {
+    pub action: OrderAction,
+    pub state: &'static str,
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot apply {:?} to an order in the {} state",
+            self.action, self.state
+        )
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl AnyOrder {
+    /// Name of the current state, used in error messages
+    pub(crate) fn state_name(&self) -> &'static str {
+        match self {
+            AnyOrder::Pending(_) => "Pending",
+            AnyOrder::Invalid(_) => "Invalid",
+            AnyOrder::AwaitingPayment(_) => "AwaitingPayment",
+            AnyOrder::PaymentFailed(_) => "PaymentFailed",
+            AnyOrder::Packaging(_) => "Packaging(PickingItems)",
+            AnyOrder::Boxing(_) => "Packaging(Boxing)",
+            AnyOrder::AwaitingPickup(_) => "Packaging(AwaitingPickup)",
+            AnyOrder::InDelivery(_) => "InDelivery",
+            AnyOrder::Completed(_) => "Completed",
+            AnyOrder::Cancelled(_) => "Cancelled",
+        }
+    }
+
+    /// Apply an action to the order, performing the corresponding transition
+    /// if it is legal for the current state
+    ///
+    /// On an illegal action, the unchanged order is returned alongside a
+    /// descriptive error so callers can inspect or retry. The error is boxed
+    /// because it embeds the whole (potentially large) `AnyOrder`.
+    pub fn apply(self, action: OrderAction) -> Result<AnyOrder, Box<(AnyOrder, TransitionError)>> {
+        match (self, action) {
+            (AnyOrder::Pending(order), OrderAction::Validate) => match order.validate() {
+                Ok(order) => Ok(AnyOrder::AwaitingPayment(order)),
+                Err(order) => Ok(AnyOrder::Invalid(order)),
+            },
+            (AnyOrder::Pending(order), OrderAction::Submit) => {
+                Ok(AnyOrder::AwaitingPayment(order.submit()))
+            }
+            (AnyOrder::Pending(order), OrderAction::Cancel { reason }) => {
+                Ok(AnyOrder::Cancelled(order.cancel(reason)))
+            }
+            (AnyOrder::AwaitingPayment(order), OrderAction::ConfirmPayment) => {
+                Ok(AnyOrder::Packaging(order.confirm_payment()))
+            }
+            (AnyOrder::AwaitingPayment(order), OrderAction::RejectPayment { reason }) => {
+                Ok(AnyOrder::PaymentFailed(order.reject_payment(reason)))
+            }
+            (AnyOrder::Packaging(order), OrderAction::PickComplete) => {
+                Ok(AnyOrder::Boxing(order.pick_complete()))
+            }
+            (AnyOrder::Packaging(order), OrderAction::Cancel { reason }) => {
+                Ok(AnyOrder::Cancelled(order.cancel(reason)))
+            }
+            (AnyOrder::Boxing(order), OrderAction::Boxed { box_count }) => {
+                Ok(AnyOrder::AwaitingPickup(order.boxed(box_count)))
+            }
+            (AnyOrder::Boxing(order), OrderAction::Cancel { reason }) => {
+                Ok(AnyOrder::Cancelled(order.cancel(reason)))
+            }
+            (AnyOrder::AwaitingPickup(order), OrderAction::Ship { tracking_id }) => {
+                Ok(AnyOrder::InDelivery(order.ship(tracking_id)))
+            }
+            (AnyOrder::AwaitingPickup(order), OrderAction::Cancel { reason }) => {
+                Ok(AnyOrder::Cancelled(order.cancel(reason)))
+            }
+            (AnyOrder::InDelivery(order), OrderAction::Complete) => {
+                Ok(AnyOrder::Completed(order.complete()))
+            }
+            (order, action) => {
+                let state = order.state_name();
+                Err(Box::new((order, TransitionError { action, state })))
+            }
+        }
+    }
+}
+
+impl From<Order<Pending>> for AnyOrder {
+    fn from(order: Order<Pending>) -> Self {
+        AnyOrder::Pending(order)
+    }
+}
+
+impl From<Order<Invalid>> for AnyOrder {
+    fn from(order: Order<Invalid>) -> Self {
+        AnyOrder::Invalid(order)
+    }
+}
+
+impl From<Order<AwaitingPayment>> for AnyOrder {
+    fn from(order: Order<AwaitingPayment>) -> Self {
+        AnyOrder::AwaitingPayment(order)
+    }
+}
+
+impl From<Order<PaymentFailed>> for AnyOrder {
+    fn from(order: Order<PaymentFailed>) -> Self {
+        AnyOrder::PaymentFailed(order)
+    }
+}
+
+impl From<Order<Packaging<PickingItems>>> for AnyOrder {
+    fn from(order: Order<Packaging<PickingItems>>) -> Self {
+        AnyOrder::Packaging(order)
+    }
+}
+
+impl From<Order<Packaging<Boxing>>> for AnyOrder {
+    fn from(order: Order<Packaging<Boxing>>) -> Self {
+        AnyOrder::Boxing(order)
+    }
+}
+
+impl From<Order<Packaging<AwaitingPickup>>> for AnyOrder {
+    fn from(order: Order<Packaging<AwaitingPickup>>) -> Self {
+        AnyOrder::AwaitingPickup(order)
+    }
+}
+
+impl From<Order<InDelivery>> for AnyOrder {
+    fn from(order: Order<InDelivery>) -> Self {
+        AnyOrder::InDelivery(order)
+    }
+}
+
+impl From<Order<Completed>> for AnyOrder {
+    fn from(order: Order<Completed>) -> Self {
+        AnyOrder::Completed(order)
+    }
+}
+
+impl From<Order<Cancelled>> for AnyOrder {
+    fn from(order: Order<Cancelled>) -> Self {
+        AnyOrder::Cancelled(order)
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Pending> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Pending(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Invalid> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Invalid(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<AwaitingPayment> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::AwaitingPayment(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<PaymentFailed> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::PaymentFailed(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Packaging<PickingItems>> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Packaging(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Packaging<Boxing>> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Boxing(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Packaging<AwaitingPickup>> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::AwaitingPickup(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<InDelivery> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::InDelivery(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Completed> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Completed(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+impl TryFrom<AnyOrder> for Order<Cancelled> {
+    type Error = AnyOrder;
+
+    fn try_from(order: AnyOrder) -> Result<Self, Self::Error> {
+        match order {
+            AnyOrder::Cancelled(order) => Ok(order),
+            order => Err(order),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Product, User};
+
+    fn order() -> Order<Pending> {
+        Order::new(
+            User::new("user-1"),
+            vec![Product::new("product-1", "Product 1", 10.0)],
+        )
+    }
+
+    #[test]
+    fn apply_drives_legal_transitions() {
+        let state = AnyOrder::from(order());
+        let state = match state.apply(OrderAction::Submit) {
+            Ok(state) => state,
+            Err(_) => panic!("expected Submit to be a legal action for a pending order"),
+        };
+        assert!(matches!(state, AnyOrder::AwaitingPayment(_)));
+        let state = match state.apply(OrderAction::ConfirmPayment) {
+            Ok(state) => state,
+            Err(_) => panic!("expected ConfirmPayment to be legal while awaiting payment"),
+        };
+        assert!(matches!(state, AnyOrder::Packaging(_)));
+        let state = match state.apply(OrderAction::PickComplete) {
+            Ok(state) => state,
+            Err(_) => panic!("expected PickComplete to be legal while picking items"),
+        };
+        assert!(matches!(state, AnyOrder::Boxing(_)));
+    }
+
+    #[test]
+    fn apply_rejects_illegal_transition() {
+        let state = AnyOrder::from(order());
+        match state.apply(OrderAction::Complete) {
+            Ok(_) => panic!("expected Complete to be illegal for a pending order"),
+            Err(boxed) => {
+                let (state, err) = *boxed;
+                assert!(matches!(state, AnyOrder::Pending(_)));
+                assert_eq!(err.state, "Pending");
+            }
+        }
+    }
+
+    #[test]
+    fn try_from_any_order_round_trips() {
+        let state = AnyOrder::from(order());
+        let order: Order<Pending> = match state.try_into() {
+            Ok(order) => order,
+            Err(_) => panic!("expected a pending AnyOrder to convert back into Order<Pending>"),
+        };
+        let state = AnyOrder::from(order.submit());
+        let err: Result<Order<Pending>, AnyOrder> = state.try_into();
+        assert!(err.is_err());
+    }
+}
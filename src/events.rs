@@ -0,0 +1,399 @@
+//! Event-sourced reconstruction of [`Order`] state.
+//!
+//! Instead of only building an `Order` by chaining typestate methods, this
+//! module lets one be rebuilt from a log of facts about what already
+//! happened to it. [`decide`] turns a requested [`OrderCommand`] into the
+//! [`OrderEvent`]s it produces (or rejects it if it doesn't make sense for
+//! the current state), and [`evolve`] folds a single event into the next
+//! state. [`Order::replay`] applies a whole log this way to reconstruct an
+//! [`AnyOrder`], since the resulting state isn't known until runtime.
+
+use crate::{
+    AnyOrder, AwaitingPayment, Cancelled, Completed, Invalid, Order, Packaging, PaymentFailed,
+    Pending, PickingItems, Product, User,
+};
+
+/// A fact about something that has already happened to an order
+#[derive(Clone, Debug)]
+pub enum OrderEvent {
+    /// The order was created
+    Created { user: User, products: Vec<Product> },
+    /// The order was submitted and is waiting for payment to be confirmed
+    Submitted,
+    /// The order passed validation and is waiting for payment to be confirmed
+    Validated,
+    /// The order failed validation
+    Invalidated { errors: Vec<String> },
+    /// Payment for the order was confirmed, and it moved to packaging
+    PaymentConfirmed,
+    /// Payment for the order could not be verified
+    PaymentRejected { reason: String },
+    /// The order was shipped
+    Shipped { tracking_id: String },
+    /// The order was completed
+    Completed,
+    /// The order was cancelled
+    Cancelled { reason: String },
+}
+
+/// A request that an order transition to a new state
+#[derive(Clone, Debug)]
+pub enum OrderCommand {
+    /// Create a new order
+    Create { user: User, products: Vec<Product> },
+    /// Submit the order, skipping validation
+    Submit,
+    /// Validate the order before submitting it
+    Validate,
+    /// Confirm that payment was received
+    ConfirmPayment,
+    /// Reject the order because payment could not be verified
+    RejectPayment { reason: String },
+    /// Ship the order
+    Ship { tracking_id: String },
+    /// Complete the order
+    Complete,
+    /// Cancel the order
+    Cancel { reason: String },
+}
+
+/// An error produced when a command cannot be applied to the current state
+#[derive(Clone, Debug)]
+pub enum OrderError {
+    /// An order already exists, so it cannot be created again
+    AlreadyCreated,
+    /// No order exists yet, so no command other than `Create` applies
+    NotCreated,
+    /// The command does not apply to the order's current state
+    InvalidCommand {
+        command: OrderCommand,
+        state: &'static str,
+    },
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::AlreadyCreated => write!(f, "order has already been created"),
+            OrderError::NotCreated => write!(f, "order has not been created yet"),
+            OrderError::InvalidCommand { command, state } => write!(
+                f,
+                "cannot apply {command:?} to an order in the {state} state"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+/// Decide which events, if any, a command should produce given the order's
+/// current state
+///
+/// `state` is `None` when no order has been created yet, in which case the
+/// only command that can succeed is `Create`.
+pub fn decide(
+    command: OrderCommand,
+    state: Option<&AnyOrder>,
+) -> Result<Vec<OrderEvent>, OrderError> {
+    let state = match (state, &command) {
+        (None, OrderCommand::Create { user, products }) => {
+            return Ok(vec![OrderEvent::Created {
+                user: user.clone(),
+                products: products.clone(),
+            }])
+        }
+        (None, _) => return Err(OrderError::NotCreated),
+        (Some(_), OrderCommand::Create { .. }) => return Err(OrderError::AlreadyCreated),
+        (Some(state), _) => state,
+    };
+
+    match (state, command) {
+        (AnyOrder::Pending(_), OrderCommand::Submit) => Ok(vec![OrderEvent::Submitted]),
+        (AnyOrder::Pending(order), OrderCommand::Validate) => {
+            let mut errors = Vec::new();
+            if order.products.is_empty() {
+                errors.push("order must contain at least one product".to_string());
+            }
+            if order.user.id.is_empty() {
+                errors.push("order must have a user".to_string());
+            }
+            if errors.is_empty() {
+                Ok(vec![OrderEvent::Validated])
+            } else {
+                Ok(vec![OrderEvent::Invalidated { errors }])
+            }
+        }
+        (AnyOrder::Pending(_), OrderCommand::Cancel { reason }) => {
+            Ok(vec![OrderEvent::Cancelled { reason }])
+        }
+        (AnyOrder::AwaitingPayment(_), OrderCommand::ConfirmPayment) => {
+            Ok(vec![OrderEvent::PaymentConfirmed])
+        }
+        (AnyOrder::AwaitingPayment(_), OrderCommand::RejectPayment { reason }) => {
+            Ok(vec![OrderEvent::PaymentRejected { reason }])
+        }
+        (AnyOrder::Packaging(_), OrderCommand::Ship { tracking_id }) => {
+            Ok(vec![OrderEvent::Shipped { tracking_id }])
+        }
+        (AnyOrder::Packaging(_), OrderCommand::Cancel { reason }) => {
+            Ok(vec![OrderEvent::Cancelled { reason }])
+        }
+        (AnyOrder::InDelivery(_), OrderCommand::Complete) => Ok(vec![OrderEvent::Completed]),
+        (state, command) => Err(OrderError::InvalidCommand {
+            command,
+            state: state.state_name(),
+        }),
+    }
+}
+
+/// Fold a single event into the next state
+///
+/// An event that doesn't apply to the current state describes an impossible
+/// transition and is rejected, leaving the order unchanged.
+pub fn evolve(state: AnyOrder, event: &OrderEvent) -> AnyOrder {
+    match (state, event) {
+        (AnyOrder::Pending(order), OrderEvent::Submitted | OrderEvent::Validated) => {
+            AnyOrder::AwaitingPayment(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: AwaitingPayment,
+            })
+        }
+        (AnyOrder::Pending(order), OrderEvent::Invalidated { errors }) => {
+            AnyOrder::Invalid(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: Invalid {
+                    errors: errors.clone(),
+                },
+            })
+        }
+        (AnyOrder::Pending(order), OrderEvent::Cancelled { reason }) => {
+            AnyOrder::Cancelled(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: Cancelled {
+                    reason: reason.clone(),
+                },
+            })
+        }
+        (AnyOrder::AwaitingPayment(order), OrderEvent::PaymentConfirmed) => {
+            AnyOrder::Packaging(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: Packaging { step: PickingItems },
+            })
+        }
+        (AnyOrder::AwaitingPayment(order), OrderEvent::PaymentRejected { reason }) => {
+            AnyOrder::PaymentFailed(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: PaymentFailed {
+                    reason: reason.clone(),
+                },
+            })
+        }
+        // The event log doesn't carry packaging sub-step detail (picking,
+        // boxing), so a `Shipped` event fast-forwards straight through them.
+        (AnyOrder::Packaging(order), OrderEvent::Shipped { tracking_id }) => {
+            AnyOrder::InDelivery(order.pick_complete().boxed(0).ship(tracking_id.clone()))
+        }
+        (AnyOrder::Packaging(order), OrderEvent::Cancelled { reason }) => {
+            AnyOrder::Cancelled(Order {
+                id: order.id,
+                user: order.user,
+                products: order.products,
+                state: Cancelled {
+                    reason: reason.clone(),
+                },
+            })
+        }
+        (AnyOrder::InDelivery(order), OrderEvent::Completed) => AnyOrder::Completed(Order {
+            id: order.id,
+            user: order.user,
+            products: order.products,
+            state: Completed,
+        }),
+        (state, _) => state,
+    }
+}
+
+impl Order<Pending> {
+    /// Reconstruct an order from a log of past events
+    ///
+    /// The log must start with a `Created` event, or else no order was ever
+    /// created and [`OrderError::NotCreated`] is returned. Every other event
+    /// is folded onto the previous state with [`evolve`], so events that
+    /// describe an impossible transition are rejected rather than
+    /// corrupting the result.
+    pub fn replay(events: &[OrderEvent]) -> Result<AnyOrder, OrderError> {
+        let mut events = events.iter();
+        let mut state = match events.next() {
+            Some(OrderEvent::Created { user, products }) => {
+                AnyOrder::Pending(Order::new(user.clone(), products.clone()))
+            }
+            _ => return Err(OrderError::NotCreated),
+        };
+        for event in events {
+            state = evolve(state, event);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User::new("user-1")
+    }
+
+    fn products() -> Vec<Product> {
+        vec![Product::new("product-1", "Product 1", 10.0)]
+    }
+
+    #[test]
+    fn decide_and_evolve_happy_path() {
+        let created = decide(
+            OrderCommand::Create {
+                user: user(),
+                products: products(),
+            },
+            None,
+        )
+        .unwrap();
+        let state = evolve(
+            AnyOrder::Pending(Order::new(user(), products())),
+            &created[0],
+        );
+
+        let events = decide(OrderCommand::Submit, Some(&state)).unwrap();
+        let state = evolve(state, &events[0]);
+        assert!(matches!(state, AnyOrder::AwaitingPayment(_)));
+
+        let events = decide(OrderCommand::ConfirmPayment, Some(&state)).unwrap();
+        let state = evolve(state, &events[0]);
+        assert!(matches!(state, AnyOrder::Packaging(_)));
+
+        let events = decide(
+            OrderCommand::Ship {
+                tracking_id: "tracking-id".to_string(),
+            },
+            Some(&state),
+        )
+        .unwrap();
+        let state = evolve(state, &events[0]);
+        assert!(matches!(state, AnyOrder::InDelivery(_)));
+
+        let events = decide(OrderCommand::Complete, Some(&state)).unwrap();
+        let state = evolve(state, &events[0]);
+        assert!(matches!(state, AnyOrder::Completed(_)));
+    }
+
+    #[test]
+    fn decide_rejects_command_for_wrong_state() {
+        let state = AnyOrder::Pending(Order::new(user(), products()));
+        let err = decide(OrderCommand::Complete, Some(&state)).unwrap_err();
+        assert!(matches!(
+            err,
+            OrderError::InvalidCommand {
+                command: OrderCommand::Complete,
+                state: "Pending",
+            }
+        ));
+    }
+
+    #[test]
+    fn replay_reconstructs_state_from_events() {
+        let events = vec![
+            OrderEvent::Created {
+                user: user(),
+                products: products(),
+            },
+            OrderEvent::Submitted,
+            OrderEvent::PaymentConfirmed,
+            OrderEvent::Shipped {
+                tracking_id: "tracking-id".to_string(),
+            },
+        ];
+        let state = Order::replay(&events).unwrap();
+        match state {
+            AnyOrder::InDelivery(order) => assert_eq!(order.state.tracking_id, "tracking-id"),
+            _ => panic!("expected order to be in delivery"),
+        }
+    }
+
+    #[test]
+    fn replay_rejects_impossible_transition() {
+        let events = vec![
+            OrderEvent::Created {
+                user: user(),
+                products: products(),
+            },
+            OrderEvent::Completed,
+        ];
+        let state = Order::replay(&events).unwrap();
+        assert!(matches!(state, AnyOrder::Pending(_)));
+    }
+
+    #[test]
+    fn replay_rejects_log_without_leading_created_event() {
+        let events = vec![OrderEvent::Submitted];
+        match Order::replay(&events) {
+            Err(OrderError::NotCreated) => {}
+            _ => panic!("expected replay to reject a log without a leading `Created` event"),
+        }
+    }
+
+    #[test]
+    fn replay_rejects_empty_log() {
+        match Order::replay(&[]) {
+            Err(OrderError::NotCreated) => {}
+            _ => panic!("expected replay to reject an empty log"),
+        }
+    }
+
+    #[test]
+    fn replay_requires_payment_confirmation_before_packaging() {
+        // `Shipped` without a preceding `PaymentConfirmed` is an impossible
+        // transition from `AwaitingPayment`, so it must be rejected rather
+        // than skipping the payment gate straight into `Packaging`.
+        let events = vec![
+            OrderEvent::Created {
+                user: user(),
+                products: products(),
+            },
+            OrderEvent::Submitted,
+            OrderEvent::Shipped {
+                tracking_id: "tracking-id".to_string(),
+            },
+        ];
+        let state = Order::replay(&events).unwrap();
+        assert!(matches!(state, AnyOrder::AwaitingPayment(_)));
+    }
+
+    #[test]
+    fn replay_follows_payment_rejection() {
+        let events = vec![
+            OrderEvent::Created {
+                user: user(),
+                products: products(),
+            },
+            OrderEvent::Validated,
+            OrderEvent::PaymentRejected {
+                reason: "card declined".to_string(),
+            },
+        ];
+        let state = Order::replay(&events).unwrap();
+        match state {
+            AnyOrder::PaymentFailed(order) => assert_eq!(order.state.reason, "card declined"),
+            _ => panic!("expected order to have a failed payment"),
+        }
+    }
+}
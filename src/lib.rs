@@ -1,12 +1,19 @@
 //! # Order data
 //!
 //! This module contains a definition of the `Order` type using a typestate
-//! pattern. Orders can be in one of four states:
+//! pattern. Orders can be in one of the following states:
 //!
 //! * `Pending`: The order has been created but not yet submitted for packaging.
-//! * `Packaging`: The order is being packaged in a warehouse.
+//! * `Invalid`: The order failed validation and cannot be submitted.
+//! * `AwaitingPayment`: The order has been submitted and is waiting for payment
+//!   to be confirmed.
+//! * `PaymentFailed`: Payment for the order could not be verified.
+//! * `Packaging<P>`: The order is being packaged in a warehouse. This state is
+//!   itself a small nested typestate (see the [`packaging`] module) tracking
+//!   whether items are being picked, boxed, or are ready for pickup.
 //! * `InDelivery`: The order is being delivered to the customer.
 //! * `Completed`: The order has been processed.
+//! * `Cancelled`: The order was cancelled before it reached the customer.
 //!
 //! By using the typestate pattern, we can ensure that we cannot run any operation
 //! that wouldn't make sense given the state of the order. For example, we cannot
@@ -28,8 +35,13 @@
 //! let user = User::new("user-123");
 //! let order = Order::new(user, vec![product]);
 //!
-//! // Submit the order for packaging
+//! // Submit the order and wait for payment to clear
 //! let order = order.submit();
+//! let order = order.confirm_payment();
+//!
+//! // Pack the order in the warehouse
+//! let order = order.pick_complete();
+//! let order = order.boxed(1);
 //!
 //! // Send the order
 //! let order = order.ship("tracking-123");
@@ -56,6 +68,16 @@
 
 use std::cmp::PartialEq;
 
+mod any_order;
+mod events;
+#[cfg(feature = "model-check")]
+pub mod model_check;
+mod packaging;
+
+pub use any_order::{AnyOrder, OrderAction, TransitionError};
+pub use events::{decide, evolve, OrderCommand, OrderError, OrderEvent};
+pub use packaging::{AwaitingPickup, Boxing, Packaging, PackagingState, PickingItems};
+
 #[derive(Clone, Debug)]
 pub struct Product {
     pub id: String,
@@ -105,9 +127,19 @@ pub trait OrderState {}
 /// State of an order that was just created
 #[derive(PartialEq, Clone, Debug)]
 pub struct Pending;
-/// Order being packaged
+/// Order that failed validation and cannot be submitted
+#[derive(PartialEq, Clone, Debug)]
+pub struct Invalid {
+    pub errors: Vec<String>,
+}
+/// Order submitted and waiting for payment to be confirmed
 #[derive(PartialEq, Clone, Debug)]
-pub struct Packaging;
+pub struct AwaitingPayment;
+/// Order whose payment could not be verified
+#[derive(PartialEq, Clone, Debug)]
+pub struct PaymentFailed {
+    pub reason: String,
+}
 /// Order being shipped
 #[derive(PartialEq, Clone, Debug)]
 pub struct InDelivery {
@@ -116,11 +148,19 @@ pub struct InDelivery {
 /// Order has been delivered
 #[derive(PartialEq, Clone, Debug)]
 pub struct Completed;
+/// Order that was cancelled before it reached the customer
+#[derive(PartialEq, Clone, Debug)]
+pub struct Cancelled {
+    pub reason: String,
+}
 
 impl OrderState for Pending {}
-impl OrderState for Packaging {}
+impl OrderState for Invalid {}
+impl OrderState for AwaitingPayment {}
+impl OrderState for PaymentFailed {}
 impl OrderState for InDelivery {}
 impl OrderState for Completed {}
+impl OrderState for Cancelled {}
 
 impl Order<Pending> {
     /// Create a new order
@@ -133,26 +173,78 @@ impl Order<Pending> {
         }
     }
 
-    /// Submit the order for packaging
-    pub fn submit(self) -> Order<Packaging> {
+    /// Submit the order and wait for payment to be confirmed
+    pub fn submit(self) -> Order<AwaitingPayment> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: AwaitingPayment,
+        }
+    }
+
+    /// Validate the order before submitting it
+    ///
+    /// Returns the order in the `AwaitingPayment` state if it is valid, or in
+    /// the `Invalid` state carrying the list of validation errors otherwise.
+    pub fn validate(self) -> Result<Order<AwaitingPayment>, Order<Invalid>> {
+        let mut errors = Vec::new();
+        if self.products.is_empty() {
+            errors.push("order must contain at least one product".to_string());
+        }
+        if self.user.id.is_empty() {
+            errors.push("order must have a user".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(Order {
+                id: self.id,
+                user: self.user,
+                products: self.products,
+                state: AwaitingPayment,
+            })
+        } else {
+            Err(Order {
+                id: self.id,
+                user: self.user,
+                products: self.products,
+                state: Invalid { errors },
+            })
+        }
+    }
+
+    /// Cancel the order
+    pub fn cancel(self, reason: impl AsRef<str>) -> Order<Cancelled> {
         Order {
             id: self.id,
             user: self.user,
             products: self.products,
-            state: Packaging,
+            state: Cancelled {
+                reason: reason.as_ref().to_string(),
+            },
         }
     }
 }
 
-impl Order<Packaging> {
-    /// Ship the order
-    pub fn ship(self, tracking_id: impl AsRef<str>) -> Order<InDelivery> {
+impl Order<AwaitingPayment> {
+    /// Confirm that payment was received and move the order to packaging
+    pub fn confirm_payment(self) -> Order<Packaging<PickingItems>> {
         Order {
             id: self.id,
             user: self.user,
             products: self.products,
-            state: InDelivery {
-                tracking_id: tracking_id.as_ref().to_string(),
+            state: Packaging { step: PickingItems },
+        }
+    }
+
+    /// Reject the order because payment could not be verified
+    pub fn reject_payment(self, reason: impl AsRef<str>) -> Order<PaymentFailed> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: PaymentFailed {
+                reason: reason.as_ref().to_string(),
             },
         }
     }
@@ -184,7 +276,13 @@ mod tests {
         let order = Order::new(user, products);
         assert_eq!(order.state, Pending);
         let order = order.submit();
-        assert_eq!(order.state, Packaging);
+        assert_eq!(order.state, AwaitingPayment);
+        let order = order.confirm_payment();
+        assert_eq!(order.state, Packaging { step: PickingItems });
+        let order = order.pick_complete();
+        assert_eq!(order.state.step, Boxing { box_count: 0 });
+        let order = order.boxed(2);
+        assert_eq!(order.state.step, AwaitingPickup { box_count: 2 });
         let order = order.ship("tracking-id");
         assert_eq!(
             order.state,
@@ -195,4 +293,74 @@ mod tests {
         let order = order.complete();
         assert_eq!(order.state, Completed);
     }
+
+    #[test]
+    fn validate_rejects_empty_products() {
+        let user = User::new("user-1");
+        let order = Order::new(user, vec![]);
+        match order.validate() {
+            Ok(_) => panic!("expected validation to fail"),
+            Err(order) => assert_eq!(
+                order.state,
+                Invalid {
+                    errors: vec!["order must contain at least one product".to_string()]
+                }
+            ),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_valid_order() {
+        let user = User::new("user-1");
+        let products = vec![Product::new("product-1", "Product 1", 10.0)];
+        let order = Order::new(user, products);
+        match order.validate() {
+            Ok(order) => assert_eq!(order.state, AwaitingPayment),
+            Err(_) => panic!("expected validation to succeed"),
+        }
+    }
+
+    #[test]
+    fn reject_payment() {
+        let user = User::new("user-1");
+        let products = vec![Product::new("product-1", "Product 1", 10.0)];
+        let order = Order::new(user, products).submit();
+        let order = order.reject_payment("card declined");
+        assert_eq!(
+            order.state,
+            PaymentFailed {
+                reason: "card declined".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn cancel_from_pending() {
+        let user = User::new("user-1");
+        let products = vec![Product::new("product-1", "Product 1", 10.0)];
+        let order = Order::new(user, products);
+        let order = order.cancel("out of stock");
+        assert_eq!(
+            order.state,
+            Cancelled {
+                reason: "out of stock".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn cancel_from_packaging() {
+        let user = User::new("user-1");
+        let products = vec![Product::new("product-1", "Product 1", 10.0)];
+        let order = Order::new(user, products)
+            .submit()
+            .confirm_payment()
+            .cancel("warehouse fire");
+        assert_eq!(
+            order.state,
+            Cancelled {
+                reason: "warehouse fire".to_string()
+            }
+        );
+    }
 }
@@ -0,0 +1,182 @@
+//! Exhaustive verification of the order lifecycle.
+//!
+//! The typestate pattern stops the *code* from performing an illegal
+//! transition, but it doesn't prove anything about the *shape* of the state
+//! machine itself. This module re-expresses the order lifecycle as a small
+//! runtime-checkable system -- a [`OrderState`] enum, an [`Action`] enum, and
+//! a [`OrderSystem::next_state`] transition function -- so invariants such as
+//! "`Completed` and `Cancelled` are terminal" can be verified by exhaustively
+//! exploring every reachable state rather than trusted by inspection.
+//!
+//! [`OrderState`] deliberately mirrors only the *shape* of the typestate
+//! lifecycle, not the data each state in [`crate`] carries (`Invalid`'s
+//! `errors`, `PaymentFailed`'s `reason`, `InDelivery`'s `tracking_id`, ...).
+//! Those payloads are already guaranteed by the type system: the only way to
+//! reach `Order<InDelivery>` is through [`Order::ship`](crate::Order::ship)'s
+//! typestate method or the [`evolve`](crate::evolve) `Shipped` arm, both of
+//! which require a `tracking_id` to construct it, so there's no
+//! path-exploration property to check. What *is* worth exhaustively checking
+//! here are properties of the transition graph itself, like which states are
+//! terminal and which paths reach a given state -- that's what this module
+//! covers.
+//!
+//! Requires the `model-check` feature.
+
+use std::collections::{HashSet, VecDeque};
+
+/// A state of the order lifecycle, mirroring the typestate variants
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum OrderState {
+    Pending,
+    Invalid,
+    AwaitingPayment,
+    PaymentFailed,
+    Packaging,
+    Boxing,
+    AwaitingPickup,
+    InDelivery,
+    Completed,
+    Cancelled,
+}
+
+/// An action that may or may not be legal for a given [`OrderState`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Validate,
+    Submit,
+    ConfirmPayment,
+    RejectPayment,
+    PickComplete,
+    Boxed,
+    Ship,
+    Complete,
+    Cancel,
+}
+
+const ALL_ACTIONS: &[Action] = &[
+    Action::Validate,
+    Action::Submit,
+    Action::ConfirmPayment,
+    Action::RejectPayment,
+    Action::PickComplete,
+    Action::Boxed,
+    Action::Ship,
+    Action::Complete,
+    Action::Cancel,
+];
+
+/// The order lifecycle, checkable as a state machine
+pub struct OrderSystem;
+
+impl OrderSystem {
+    /// States an order can start in
+    pub fn init_states() -> Vec<OrderState> {
+        vec![OrderState::Pending]
+    }
+
+    /// The state reached by applying `action` to `state`, or `None` if the
+    /// action is illegal for that state
+    ///
+    /// `Validate` is modelled by its passing outcome only, so
+    /// [`OrderState::Invalid`] is never returned and is not reachable from
+    /// [`OrderSystem::init_states`].
+    pub fn next_state(state: OrderState, action: Action) -> Option<OrderState> {
+        use Action::*;
+        use OrderState::*;
+
+        match (state, action) {
+            (Pending, Validate) => Some(AwaitingPayment),
+            (Pending, Submit) => Some(AwaitingPayment),
+            (Pending, Cancel) => Some(Cancelled),
+            (AwaitingPayment, ConfirmPayment) => Some(Packaging),
+            (AwaitingPayment, RejectPayment) => Some(PaymentFailed),
+            (Packaging, PickComplete) => Some(Boxing),
+            (Packaging, Cancel) => Some(Cancelled),
+            (Boxing, Boxed) => Some(AwaitingPickup),
+            (Boxing, Cancel) => Some(Cancelled),
+            (AwaitingPickup, Ship) => Some(InDelivery),
+            (AwaitingPickup, Cancel) => Some(Cancelled),
+            (InDelivery, Complete) => Some(Completed),
+            _ => None,
+        }
+    }
+
+    /// Every state reachable from [`OrderSystem::init_states`], found by a
+    /// breadth-first exploration of all actions from all states
+    pub fn reachable_states() -> HashSet<OrderState> {
+        let mut seen: HashSet<OrderState> = HashSet::new();
+        let mut queue: VecDeque<OrderState> = Self::init_states().into();
+
+        while let Some(state) = queue.pop_front() {
+            if !seen.insert(state) {
+                continue;
+            }
+            for &action in ALL_ACTIONS {
+                if let Some(next) = OrderSystem::next_state(state, action) {
+                    if !seen.contains(&next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        seen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_and_cancelled_are_terminal() {
+        for &action in ALL_ACTIONS {
+            assert_eq!(OrderSystem::next_state(OrderState::Completed, action), None);
+            assert_eq!(OrderSystem::next_state(OrderState::Cancelled, action), None);
+        }
+    }
+
+    #[test]
+    fn in_delivery_is_only_reached_by_shipping() {
+        for &state in OrderSystem::reachable_states().iter() {
+            for &action in ALL_ACTIONS {
+                if OrderSystem::next_state(state, action) == Some(OrderState::InDelivery) {
+                    assert_eq!(action, Action::Ship);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn completed_is_only_reachable_through_in_delivery() {
+        for &state in OrderSystem::reachable_states().iter() {
+            if state == OrderState::InDelivery {
+                continue;
+            }
+            for &action in ALL_ACTIONS {
+                assert_ne!(
+                    OrderSystem::next_state(state, action),
+                    Some(OrderState::Completed)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reachable_states_cover_the_whole_lifecycle() {
+        let reachable = OrderSystem::reachable_states();
+        for state in [
+            OrderState::Pending,
+            OrderState::AwaitingPayment,
+            OrderState::PaymentFailed,
+            OrderState::Packaging,
+            OrderState::Boxing,
+            OrderState::AwaitingPickup,
+            OrderState::InDelivery,
+            OrderState::Completed,
+            OrderState::Cancelled,
+        ] {
+            assert!(reachable.contains(&state), "{state:?} should be reachable");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Sub-states of the [`Packaging`] state.
+//!
+//! Packaging an order is itself a small warehouse workflow: items are picked,
+//! boxed up, and left ready for pickup before the order can ship. Rather than
+//! treat `Packaging` as a single opaque state, this module models those steps
+//! as their own nested typestate, so `Order<Packaging<P>>` only exposes the
+//! methods that make sense at step `P`. The top-level API is unaffected --
+//! [`Order::confirm_payment`](crate::Order::confirm_payment) still lands on
+//! packaging, and only `Order<Packaging<AwaitingPickup>>` can `ship`.
+
+use crate::{Cancelled, InDelivery, Order, OrderState};
+
+/// A step of the packaging workflow
+pub trait PackagingState {}
+
+/// Items for the order are being picked from the warehouse shelves
+#[derive(PartialEq, Clone, Debug)]
+pub struct PickingItems;
+/// Picked items are being packed into boxes
+#[derive(PartialEq, Clone, Debug)]
+pub struct Boxing {
+    pub box_count: u32,
+}
+/// Boxed order is ready for the carrier to pick up
+#[derive(PartialEq, Clone, Debug)]
+pub struct AwaitingPickup {
+    pub box_count: u32,
+}
+
+impl PackagingState for PickingItems {}
+impl PackagingState for Boxing {}
+impl PackagingState for AwaitingPickup {}
+
+/// Order being packaged in a warehouse
+///
+/// `P` tracks which step of the packaging workflow the order is currently at.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Packaging<P: PackagingState> {
+    pub step: P,
+}
+
+impl<P: PackagingState> OrderState for Packaging<P> {}
+
+impl Order<Packaging<PickingItems>> {
+    /// Finish picking items and start boxing them up
+    pub fn pick_complete(self) -> Order<Packaging<Boxing>> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: Packaging {
+                step: Boxing { box_count: 0 },
+            },
+        }
+    }
+}
+
+impl Order<Packaging<Boxing>> {
+    /// Finish boxing the order, recording how many boxes it took
+    pub fn boxed(self, box_count: u32) -> Order<Packaging<AwaitingPickup>> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: Packaging {
+                step: AwaitingPickup { box_count },
+            },
+        }
+    }
+}
+
+impl Order<Packaging<AwaitingPickup>> {
+    /// Hand the order to the carrier and start delivery
+    pub fn ship(self, tracking_id: impl AsRef<str>) -> Order<InDelivery> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: InDelivery {
+                tracking_id: tracking_id.as_ref().to_string(),
+            },
+        }
+    }
+}
+
+impl<P: PackagingState> Order<Packaging<P>> {
+    /// Cancel the order
+    pub fn cancel(self, reason: impl AsRef<str>) -> Order<Cancelled> {
+        Order {
+            id: self.id,
+            user: self.user,
+            products: self.products,
+            state: Cancelled {
+                reason: reason.as_ref().to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Product, User};
+
+    fn order() -> Order<Packaging<PickingItems>> {
+        Order {
+            id: String::new(),
+            user: User::new("user-1"),
+            products: vec![Product::new("product-1", "Product 1", 10.0)],
+            state: Packaging { step: PickingItems },
+        }
+    }
+
+    #[test]
+    fn packaging_steps_in_order() {
+        let order = order();
+        let order = order.pick_complete();
+        assert_eq!(order.state.step, Boxing { box_count: 0 });
+        let order = order.boxed(3);
+        assert_eq!(order.state.step, AwaitingPickup { box_count: 3 });
+        let order = order.ship("tracking-id");
+        assert_eq!(order.state.tracking_id, "tracking-id");
+    }
+
+    #[test]
+    fn cancel_from_any_packaging_step() {
+        let picking = order().cancel("out of stock");
+        assert_eq!(picking.state.reason, "out of stock");
+        let pickup = order().pick_complete().boxed(1).cancel("carrier unavailable");
+        assert_eq!(pickup.state.reason, "carrier unavailable");
+        let order = order().pick_complete().cancel("warehouse fire");
+        assert_eq!(order.state.reason, "warehouse fire");
+    }
+}